@@ -1,7 +1,7 @@
 use core::{
     num::NonZeroU32,
     ptr::NonNull,
-    sync::atomic::{AtomicPtr, Ordering},
+    sync::atomic::{AtomicBool, AtomicPtr, AtomicU32, AtomicU64, AtomicU8, AtomicUsize, Ordering},
 };
 
 use axplat::{
@@ -9,11 +9,18 @@ use axplat::{
     percpu::this_cpu_id,
 };
 use kspin::SpinNoIrq;
-use riscv::register::sie;
+use riscv::register::{sie, sip};
 use riscv_plic::Plic;
 use sbi_rt::HartMask;
 
-use crate::config::{devices::PLIC_PADDR, plat::PHYS_VIRT_OFFSET};
+use crate::config::{
+    devices::PLIC_PADDR,
+    plat::{CPU_NUM, PHYS_VIRT_OFFSET},
+};
+
+pub mod irq_sim;
+#[cfg(feature = "irq-timings")]
+pub mod timings;
 
 /// `Interrupt` bit in `scause`
 pub(super) const INTC_IRQ_BASE: usize = 1 << (usize::BITS - 1);
@@ -22,15 +29,48 @@ pub(super) const INTC_IRQ_BASE: usize = 1 << (usize::BITS - 1);
 #[allow(unused)]
 pub(super) const S_SOFT: usize = INTC_IRQ_BASE + 1;
 
+/// Highest `irq_num` that names a multiplexed logical IPI, i.e.
+/// `S_SOFT + MAX_IPI_COUNT - 1`.
+const S_SOFT_MAX: usize = S_SOFT + MAX_IPI_COUNT - 1;
+
 /// Supervisor timer interrupt in `scause`
 pub(super) const S_TIMER: usize = INTC_IRQ_BASE + 5;
 
+const _: () = assert!(
+    S_SOFT_MAX < S_TIMER,
+    "IPI cause range overlaps S_TIMER; shrink MAX_IPI_COUNT"
+);
+
 /// Supervisor external interrupt in `scause`
 pub(super) const S_EXT: usize = INTC_IRQ_BASE + 9;
 
 static TIMER_HANDLER: AtomicPtr<()> = AtomicPtr::new(core::ptr::null_mut());
 
-static IPI_HANDLER: AtomicPtr<()> = AtomicPtr::new(core::ptr::null_mut());
+/// Number of distinct logical IPI purposes (reschedule, TLB shootdown, ...)
+/// multiplexed over the single supervisor software interrupt. IPI handlers
+/// are registered at `S_SOFT + id` for `id` in `0..MAX_IPI_COUNT`.
+///
+/// Must stay small enough that `S_SOFT_MAX` doesn't reach `S_TIMER`, or the
+/// `with_cause!` match would dispatch IPI ids near the top of the range to
+/// the timer arm instead.
+const MAX_IPI_COUNT: usize = 4;
+
+static IPI_HANDLERS: [AtomicPtr<()>; MAX_IPI_COUNT] =
+    [const { AtomicPtr::new(core::ptr::null_mut()) }; MAX_IPI_COUNT];
+
+/// Per-CPU bitmask of pending logical IPI IDs, one bit per [`IPI_HANDLERS`]
+/// entry. [`IrqIfImpl::send_ipi`] ORs into the target's mask before issuing
+/// the underlying SBI software interrupt, and the `S_SOFT` handler in
+/// [`IrqIfImpl::handle`] atomically drains its own mask and runs every
+/// handler whose bit was set.
+static IPI_PENDING: [AtomicUsize; CPU_NUM] = [const { AtomicUsize::new(0) }; CPU_NUM];
+
+/// Extracts the logical IPI id encoded in a `register`/`send_ipi` `irq_num`
+/// (`S_SOFT + id`). The result is `>= MAX_IPI_COUNT` if `irq_num` doesn't
+/// name a valid one (`MAX_IPI_COUNT` itself if `irq_num` is below `S_SOFT`).
+fn ipi_id(irq_num: usize) -> usize {
+    irq_num.checked_sub(S_SOFT).unwrap_or(MAX_IPI_COUNT)
+}
 
 /// The maximum number of IRQs.
 pub const MAX_IRQ_COUNT: usize = 1024;
@@ -41,13 +81,149 @@ static PLIC: SpinNoIrq<Plic> = SpinNoIrq::new(unsafe {
     Plic::new(NonNull::new((PHYS_VIRT_OFFSET + PLIC_PADDR) as *mut _).unwrap())
 });
 
+/// Per-CPU, per-cause interrupt counters, bumped once per delivered
+/// interrupt on the hot path.
+struct CpuIrqStats {
+    timer: AtomicU64,
+    soft: AtomicU64,
+    ext: AtomicU64,
+}
+
+static CPU_IRQ_STATS: [CpuIrqStats; CPU_NUM] = [const {
+    CpuIrqStats {
+        timer: AtomicU64::new(0),
+        soft: AtomicU64::new(0),
+        ext: AtomicU64::new(0),
+    }
+}; CPU_NUM];
+
+/// Per-(irq, cpu) delivery counters for external PLIC lines, keyed by the
+/// IRQ number claimed from the PLIC.
+static IRQ_CPU_STATS: [[AtomicU64; CPU_NUM]; MAX_IRQ_COUNT] =
+    [const { [const { AtomicU64::new(0) }; CPU_NUM] }; MAX_IRQ_COUNT];
+
+/// Returns the `(timer, software, external)` interrupt counts delivered to
+/// `cpu_id` since boot, for a `/proc/interrupts`-style view.
+pub fn cpu_irq_stats(cpu_id: usize) -> (u64, u64, u64) {
+    let stats = &CPU_IRQ_STATS[cpu_id];
+    (
+        stats.timer.load(Ordering::Relaxed),
+        stats.soft.load(Ordering::Relaxed),
+        stats.ext.load(Ordering::Relaxed),
+    )
+}
+
+/// Returns how many times `irq` has been claimed and dispatched on `cpu_id`
+/// since boot.
+pub fn irq_stats(irq: usize, cpu_id: usize) -> u64 {
+    IRQ_CPU_STATS[irq][cpu_id].load(Ordering::Relaxed)
+}
+
+/// CPU ID of the hart that ran [`init_percpu`] first, used as the default
+/// affinity for IRQs that haven't had [`IrqIfImpl::set_affinity`] called on
+/// them yet.
+static BOOT_CPU_ID: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+/// Per-IRQ CPU affinity mask, one bit per CPU ID. `0` means "unset", in
+/// which case the IRQ is routed to [`BOOT_CPU_ID`].
+static IRQ_AFFINITY: [AtomicUsize; MAX_IRQ_COUNT] = [const { AtomicUsize::new(0) }; MAX_IRQ_COUNT];
+
+/// Whether `set_enable(irq, true)` has actually been called for an external
+/// IRQ (typically via `register`). A default affinity alone doesn't mean a
+/// source should be live on the PLIC, so [`rebalance_irqs`] and
+/// [`migrate_irqs_away`] gate on this instead of on `irq_affinity` alone.
+static IRQ_ENABLED: [AtomicBool; MAX_IRQ_COUNT] = [const { AtomicBool::new(false) }; MAX_IRQ_COUNT];
+
+/// Priority an external IRQ is given if `set_priority` is never called on
+/// it, matching the value this code used to hardcode.
+const DEFAULT_IRQ_PRIORITY: u8 = 6;
+
+/// Highest priority value this PLIC supports.
+const MAX_IRQ_PRIORITY: u8 = 7;
+
+/// Per-IRQ priority, remembered so a later `set_enable(irq, true)` restores
+/// the configured priority instead of resetting it to the default.
+static IRQ_PRIORITY: [AtomicU8; MAX_IRQ_COUNT] =
+    [const { AtomicU8::new(DEFAULT_IRQ_PRIORITY) }; MAX_IRQ_COUNT];
+
+/// Number of delivered interrupts sampled before [`note_interrupt`] decides
+/// whether a source is spurious, mirroring Linux's `note_interrupt`.
+const IRQ_NOTE_SAMPLE_WINDOW: u32 = 100_000;
+
+/// Above this many unhandled interrupts within a sample window, the source
+/// is considered stuck or misconfigured and gets masked.
+const IRQ_NOTE_UNHANDLED_THRESHOLD: u32 = 99_900;
+
+/// Per-IRQ counters used to detect a source that keeps firing without ever
+/// being serviced by a registered handler.
+struct IrqNoteCounters {
+    /// Interrupts delivered for this IRQ since the last reset.
+    count: AtomicU32,
+    /// Of those, how many found no handler to run.
+    unhandled: AtomicU32,
+}
+
+static IRQ_NOTE: [IrqNoteCounters; MAX_IRQ_COUNT] = [const {
+    IrqNoteCounters {
+        count: AtomicU32::new(0),
+        unhandled: AtomicU32::new(0),
+    }
+}; MAX_IRQ_COUNT];
+
+/// Masks `irq` once [`IRQ_NOTE_SAMPLE_WINDOW`] deliveries in a row have gone
+/// unhandled, loosely mirroring Linux's `note_interrupt`. Any handled
+/// delivery resets both counters, so a window never mixes handled and
+/// unhandled deliveries.
+fn note_interrupt(irq: usize, handled: bool) {
+    let counters = &IRQ_NOTE[irq];
+    if handled {
+        counters.count.store(0, Ordering::Relaxed);
+        counters.unhandled.store(0, Ordering::Relaxed);
+        return;
+    }
+
+    let count = counters.count.fetch_add(1, Ordering::Relaxed) + 1;
+    let unhandled = counters.unhandled.fetch_add(1, Ordering::Relaxed) + 1;
+    if count >= IRQ_NOTE_SAMPLE_WINDOW {
+        if unhandled > IRQ_NOTE_UNHANDLED_THRESHOLD {
+            warn!("irq {irq}: {unhandled} of the last {count} interrupts were unhandled, masking");
+            IrqIfImpl::set_enable(irq, false);
+        }
+        counters.count.store(0, Ordering::Relaxed);
+        counters.unhandled.store(0, Ordering::Relaxed);
+    }
+}
+
 fn this_context() -> usize {
-    let hart_id = this_cpu_id() + 1;
-    // hart 0 missing S-mode
-    hart_id * 2 // supervisor context
+    plic_context(this_cpu_id())
+}
+
+/// Computes the PLIC supervisor context for the given CPU ID.
+///
+/// The SiFive PLIC gives each hart a machine and a supervisor context,
+/// except hart 0, which has no S-mode context; the supervisor context of
+/// CPU `cpu_id` is therefore numbered `(cpu_id + 1) * 2`.
+fn plic_context(cpu_id: usize) -> usize {
+    (cpu_id + 1) * 2
+}
+
+/// Returns the CPU affinity mask for `irq`, defaulting to the boot hart if
+/// no affinity has been set explicitly.
+fn irq_affinity(irq: usize) -> usize {
+    match IRQ_AFFINITY[irq].load(Ordering::Acquire) {
+        0 => 1 << BOOT_CPU_ID.load(Ordering::Acquire),
+        mask => mask,
+    }
 }
 
 pub(super) fn init_percpu() {
+    let _ = BOOT_CPU_ID.compare_exchange(
+        usize::MAX,
+        this_cpu_id(),
+        Ordering::AcqRel,
+        Ordering::Acquire,
+    );
+
     // enable soft interrupts, timer interrupts, and external interrupts
     unsafe {
         sie::set_ssoft();
@@ -55,13 +231,84 @@ pub(super) fn init_percpu() {
         sie::set_sext();
     }
     PLIC.lock().init_by_context(this_context());
+
+    ONLINE_CPUS.fetch_or(1 << this_cpu_id(), Ordering::AcqRel);
+    rebalance_irqs();
+}
+
+/// Bitmask of currently online CPUs, one bit per CPU ID. Kept up to date by
+/// [`init_percpu`] and [`migrate_irqs_away`].
+static ONLINE_CPUS: AtomicUsize = AtomicUsize::new(0);
+
+/// Re-applies the stored affinity of every externally-routed IRQ that wants
+/// the calling (just-onlined) CPU, so load that had to fall back to another
+/// hart while this one was offline spreads back onto it.
+pub(super) fn rebalance_irqs() {
+    let cpu_id = this_cpu_id();
+    let mut plic = PLIC.lock();
+    for irq in 0..MAX_IRQ_COUNT {
+        if irq_sim::is_sim_irq(irq) || !IRQ_ENABLED[irq].load(Ordering::Relaxed) {
+            continue;
+        }
+        let Some(nz_irq) = NonZeroU32::new(irq as _) else {
+            continue;
+        };
+        if irq_affinity(irq) & (1 << cpu_id) != 0 {
+            plic.enable(nz_irq, plic_context(cpu_id));
+        }
+    }
+}
+
+/// Called by a hart before it goes offline: walks the affinity table and,
+/// for every external IRQ currently routed to this (departing) context,
+/// re-routes it to a surviving context instead of stranding it, respecting
+/// any user-set affinity intersected with the remaining online CPUs and
+/// falling back to the boot hart (or any other survivor) if that
+/// intersection is empty.
+pub(super) fn migrate_irqs_away() {
+    let cpu_id = this_cpu_id();
+    let online_after = ONLINE_CPUS.fetch_and(!(1 << cpu_id), Ordering::AcqRel) & !(1 << cpu_id);
+    if online_after == 0 {
+        // Last hart going offline; no survivor to migrate to.
+        return;
+    }
+
+    let mut plic = PLIC.lock();
+    for irq in 0..MAX_IRQ_COUNT {
+        if irq_sim::is_sim_irq(irq) || !IRQ_ENABLED[irq].load(Ordering::Relaxed) {
+            continue;
+        }
+        let mask = irq_affinity(irq);
+        if mask & (1 << cpu_id) == 0 {
+            continue;
+        }
+        let Some(nz_irq) = NonZeroU32::new(irq as _) else {
+            continue;
+        };
+
+        let mut target = mask & online_after;
+        if target == 0 {
+            let boot = BOOT_CPU_ID.load(Ordering::Acquire);
+            target = if online_after & (1 << boot) != 0 {
+                1 << boot
+            } else {
+                1 << online_after.trailing_zeros()
+            };
+        }
+        for survivor in 0..CPU_NUM {
+            if target & (1 << survivor) != 0 {
+                plic.enable(nz_irq, plic_context(survivor));
+            }
+        }
+        plic.disable(nz_irq, plic_context(cpu_id));
+    }
 }
 
 macro_rules! with_cause {
     ($cause: expr, @S_TIMER => $timer_op: expr, @S_SOFT => $ipi_op: expr, @S_EXT => $ext_op: expr, @EX_IRQ => $plic_op: expr $(,)?) => {
         match $cause {
             S_TIMER => $timer_op,
-            S_SOFT => $ipi_op,
+            S_SOFT..=S_SOFT_MAX => $ipi_op,
             S_EXT => $ext_op,
             other => {
                 if other & INTC_IRQ_BASE == 0 {
@@ -96,20 +343,93 @@ impl IrqIf for IrqIfImpl {
             @S_SOFT => {},
             @S_EXT => {},
             @EX_IRQ => {
-                let Some(irq) = NonZeroU32::new(irq as _) else {
+                if irq_sim::is_sim_irq(irq) {
+                    irq_sim::set_enable(irq, enabled);
+                    return;
+                }
+                let Some(nz_irq) = NonZeroU32::new(irq as _) else {
                     return;
                 };
+                // Intersect with ONLINE_CPUS so a register/unregister cycle
+                // can't re-enable the IRQ on a context that migrate_irqs_away
+                // already routed off of, even though IRQ_AFFINITY itself
+                // still names it.
+                let mask = irq_affinity(irq) & ONLINE_CPUS.load(Ordering::Acquire);
                 let mut plic = PLIC.lock();
                 if enabled {
-                    plic.set_priority(irq, 6);
-                    plic.enable(irq, this_context());
-                } else {
-                    plic.disable(irq, this_context());
+                    plic.set_priority(nz_irq, IRQ_PRIORITY[irq].load(Ordering::Relaxed));
+                }
+                for cpu_id in 0..CPU_NUM {
+                    let context = plic_context(cpu_id);
+                    if enabled && mask & (1 << cpu_id) != 0 {
+                        plic.enable(nz_irq, context);
+                    } else {
+                        plic.disable(nz_irq, context);
+                    }
                 }
+                IRQ_ENABLED[irq].store(enabled, Ordering::Relaxed);
             }
         );
     }
 
+    /// Routes the given external IRQ to the set of CPUs in `cpu_mask` (one
+    /// bit per CPU ID), enabling it on each targeted hart's PLIC context and
+    /// disabling it everywhere else.
+    ///
+    /// The affinity is remembered per-IRQ, so a later `register`/`unregister`
+    /// cycle re-applies it instead of falling back to the boot hart. This is
+    /// a no-op for the CPU-side timer and software interrupt lines, which
+    /// aren't routed through the PLIC.
+    fn set_affinity(irq: usize, cpu_mask: usize) {
+        if irq & INTC_IRQ_BASE != 0 || irq_sim::is_sim_irq(irq) {
+            return;
+        }
+        let Some(nz_irq) = NonZeroU32::new(irq as _) else {
+            return;
+        };
+        IRQ_AFFINITY[irq].store(cpu_mask, Ordering::Release);
+
+        let mut plic = PLIC.lock();
+        for cpu_id in 0..CPU_NUM {
+            let context = plic_context(cpu_id);
+            if cpu_mask & (1 << cpu_id) != 0 {
+                plic.enable(nz_irq, context);
+            } else {
+                plic.disable(nz_irq, context);
+            }
+        }
+    }
+
+    /// Sets the priority of an external IRQ source, in `0..=7`.
+    ///
+    /// The priority is remembered so a later `set_enable(irq, true)` (e.g.
+    /// after `register`/`unregister`) re-applies it instead of clobbering it
+    /// back to the default. Values above this PLIC's maximum are rejected.
+    fn set_priority(irq: usize, priority: u8) {
+        if irq & INTC_IRQ_BASE != 0 || irq_sim::is_sim_irq(irq) {
+            return;
+        }
+        let Some(nz_irq) = NonZeroU32::new(irq as _) else {
+            return;
+        };
+        if priority > MAX_IRQ_PRIORITY {
+            warn!("irq {irq}: priority {priority} exceeds max {MAX_IRQ_PRIORITY}, ignoring");
+            return;
+        }
+        IRQ_PRIORITY[irq].store(priority, Ordering::Relaxed);
+        PLIC.lock().set_priority(nz_irq, priority);
+    }
+
+    /// Sets the calling hart's PLIC context threshold, in `0..=7`.
+    ///
+    /// Sources at or below the threshold are gated out, letting a core
+    /// temporarily raise it to coalesce low-priority interrupts. Values
+    /// above this PLIC's maximum are clamped.
+    fn set_threshold(threshold: u8) {
+        let threshold = threshold.min(MAX_IRQ_PRIORITY);
+        PLIC.lock().set_threshold(this_context(), threshold);
+    }
+
     /// Registers an IRQ handler for the given IRQ.
     ///
     /// It also enables the IRQ if the registration succeeds. It returns `false` if
@@ -127,7 +447,16 @@ impl IrqIf for IrqIfImpl {
         with_cause!(
             irq,
             @S_TIMER => TIMER_HANDLER.compare_exchange(core::ptr::null_mut(), handler as *mut _, Ordering::AcqRel, Ordering::Acquire).is_ok(),
-            @S_SOFT => IPI_HANDLER.compare_exchange(core::ptr::null_mut(), handler as *mut _, Ordering::AcqRel, Ordering::Acquire).is_ok(),
+            @S_SOFT => {
+                let id = ipi_id(irq);
+                match IPI_HANDLERS.get(id) {
+                    Some(slot) => slot.compare_exchange(core::ptr::null_mut(), handler as *mut _, Ordering::AcqRel, Ordering::Acquire).is_ok(),
+                    None => {
+                        warn!("IPI id {id} out of range (max {MAX_IPI_COUNT})");
+                        false
+                    }
+                }
+            },
             @S_EXT => {
                 warn!("External IRQ should be got from PLIC, not scause");
                 false
@@ -160,11 +489,17 @@ impl IrqIf for IrqIfImpl {
                 }
             },
             @S_SOFT => {
-                let handler = IPI_HANDLER.swap(core::ptr::null_mut(), Ordering::AcqRel);
-                if !handler.is_null() {
-                    Some(unsafe { core::mem::transmute::<*mut (), IrqHandler>(handler) })
-                } else {
-                    None
+                let id = ipi_id(irq);
+                match IPI_HANDLERS.get(id) {
+                    Some(slot) => {
+                        let handler = slot.swap(core::ptr::null_mut(), Ordering::AcqRel);
+                        if !handler.is_null() {
+                            Some(unsafe { core::mem::transmute::<*mut (), IrqHandler>(handler) })
+                        } else {
+                            None
+                        }
+                    }
+                    None => None,
                 }
             },
             @S_EXT => {
@@ -185,6 +520,7 @@ impl IrqIf for IrqIfImpl {
             irq,
             @S_TIMER => {
                 trace!("IRQ: timer");
+                CPU_IRQ_STATS[this_cpu_id()].timer.fetch_add(1, Ordering::Relaxed);
                 let handler = TIMER_HANDLER.load(Ordering::Acquire);
                 if !handler.is_null() {
                     // SAFETY: The handler is guaranteed to be a valid function pointer.
@@ -194,22 +530,46 @@ impl IrqIf for IrqIfImpl {
             },
             @S_SOFT => {
                 trace!("IRQ: IPI");
-                let handler = IPI_HANDLER.load(Ordering::Acquire);
-                if !handler.is_null() {
-                    // SAFETY: The handler is guaranteed to be a valid function pointer.
-                    unsafe { core::mem::transmute::<*mut (), IrqHandler>(handler)() };
+                CPU_IRQ_STATS[this_cpu_id()].soft.fetch_add(1, Ordering::Relaxed);
+                // Demux: clear SSIP *before* draining the pending mask, so a
+                // sender that ORs a new bit in and re-raises SSIP right
+                // after our clear still leaves SSIP set (causing an
+                // immediate re-trap that will observe the bit on the next
+                // swap) instead of having it silently wiped by a clear that
+                // runs after we've already swapped the mask out.
+                unsafe { sip::clear_ssoft() };
+                let pending = IPI_PENDING[this_cpu_id()].swap(0, Ordering::AcqRel);
+                for id in 0..MAX_IPI_COUNT {
+                    if pending & (1 << id) == 0 {
+                        continue;
+                    }
+                    let handler = IPI_HANDLERS[id].load(Ordering::Acquire);
+                    if !handler.is_null() {
+                        // SAFETY: The handler is guaranteed to be a valid function pointer.
+                        unsafe { core::mem::transmute::<*mut (), IrqHandler>(handler)() };
+                    }
                 }
                 Some(irq)
             },
             @S_EXT => {
+                let cpu_id = this_cpu_id();
+                CPU_IRQ_STATS[cpu_id].ext.fetch_add(1, Ordering::Relaxed);
                 let mut plic = PLIC.lock();
                 let Some(irq) = plic.claim(this_context()) else {
                     debug!("Spurious external IRQ");
                     return None;
                 };
                 trace!("IRQ: external {irq}");
-                IRQ_HANDLER_TABLE.handle(irq.get() as usize);
-                plic.complete(this_context(), irq);
+                IRQ_CPU_STATS[irq.get() as usize][cpu_id].fetch_add(1, Ordering::Relaxed);
+                #[cfg(feature = "irq-timings")]
+                timings::record(irq.get() as usize, timings::now());
+                let handled = IRQ_HANDLER_TABLE.handle(irq.get() as usize);
+                // `note_interrupt` may mask the source via `set_enable`,
+                // which takes `PLIC` itself; drop this guard first since
+                // `SpinNoIrq` isn't reentrant.
+                drop(plic);
+                note_interrupt(irq.get() as usize, handled);
+                PLIC.lock().complete(this_context(), irq);
                 Some(irq.get() as usize)
             },
             @EX_IRQ => {
@@ -219,15 +579,29 @@ impl IrqIf for IrqIfImpl {
     }
 
     /// Sends an inter-processor interrupt (IPI) to the specified target CPU or all CPUs.
-    fn send_ipi(_irq_num: usize, target: IpiTarget) {
+    ///
+    /// `irq_num` names the logical IPI purpose (as registered via
+    /// `register(S_SOFT + id, handler)`); its bit is OR'd into each
+    /// target's pending mask before the underlying SBI software interrupt
+    /// is issued, so multiple purposes can share the single supervisor
+    /// software interrupt without stomping on each other.
+    fn send_ipi(irq_num: usize, target: IpiTarget) {
+        let id = ipi_id(irq_num);
+        if id >= MAX_IPI_COUNT {
+            warn!("IPI id {id} out of range (max {MAX_IPI_COUNT}), dropping");
+            return;
+        }
+        let bit = 1 << id;
         match target {
             IpiTarget::Current { cpu_id } => {
+                IPI_PENDING[cpu_id].fetch_or(bit, Ordering::AcqRel);
                 let res = sbi_rt::send_ipi(HartMask::from_mask_base(1 << cpu_id, 0));
                 if res.is_err() {
                     warn!("send_ipi failed: {res:?}");
                 }
             }
             IpiTarget::Other { cpu_id } => {
+                IPI_PENDING[cpu_id].fetch_or(bit, Ordering::AcqRel);
                 let res = sbi_rt::send_ipi(HartMask::from_mask_base(1 << cpu_id, 0));
                 if res.is_err() {
                     warn!("send_ipi failed: {res:?}");
@@ -236,6 +610,7 @@ impl IrqIf for IrqIfImpl {
             IpiTarget::AllExceptCurrent { cpu_id, cpu_num } => {
                 for i in 0..cpu_num {
                     if i != cpu_id {
+                        IPI_PENDING[i].fetch_or(bit, Ordering::AcqRel);
                         let res = sbi_rt::send_ipi(HartMask::from_mask_base(1 << i, 0));
                         if res.is_err() {
                             warn!("send_ipi_all_others failed: {res:?}");
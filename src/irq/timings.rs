@@ -0,0 +1,112 @@
+//! Per-source IRQ interval history, used to predict the next arrival for
+//! tickless idle, mirroring Linux's `timings.c`.
+
+use kspin::SpinNoIrq;
+
+use super::MAX_IRQ_COUNT;
+
+/// Number of recent inter-arrival intervals kept per source.
+const RING_LEN: usize = 16;
+
+/// Minimum number of recorded intervals before a prediction is attempted.
+const MIN_SAMPLES: usize = 2;
+
+/// Per-source interval history used to predict its next arrival.
+struct IrqTiming {
+    /// Timestamp of the last delivered interrupt on this source, or `0` if
+    /// none has been recorded yet.
+    last_ts: u64,
+    /// Recent inter-arrival intervals, oldest first, `0..len` valid.
+    intervals: [u64; RING_LEN],
+    len: usize,
+    /// Exponential moving average of `intervals`, used as a fallback when
+    /// no repeating period is found.
+    ema: u64,
+}
+
+impl IrqTiming {
+    const fn new() -> Self {
+        Self {
+            last_ts: 0,
+            intervals: [0; RING_LEN],
+            len: 0,
+            ema: 0,
+        }
+    }
+
+    fn record(&mut self, ts: u64) {
+        if self.last_ts != 0 {
+            let interval = ts.saturating_sub(self.last_ts);
+            if self.len < RING_LEN {
+                self.intervals[self.len] = interval;
+                self.len += 1;
+            } else {
+                self.intervals.copy_within(1.., 0);
+                self.intervals[RING_LEN - 1] = interval;
+            }
+            self.ema = if self.ema == 0 {
+                interval
+            } else {
+                (self.ema * 3 + interval) / 4
+            };
+        }
+        self.last_ts = ts;
+    }
+
+    /// Predicts the next arrival, or `None` if too few samples have been
+    /// recorded yet.
+    fn predict_next(&self) -> Option<u64> {
+        if self.last_ts == 0 || self.len < MIN_SAMPLES {
+            return None;
+        }
+        Some(self.last_ts + self.predicted_interval())
+    }
+
+    /// Finds the longest repeating suffix of recorded intervals (by coarse
+    /// log2 bucket), falling back to the EMA if none is found.
+    fn predicted_interval(&self) -> u64 {
+        let buf = &self.intervals[..self.len];
+        for period in (1..=buf.len() / 2).rev() {
+            let suffix = &buf[buf.len() - period..];
+            let prev = &buf[buf.len() - 2 * period..buf.len() - period];
+            if suffix
+                .iter()
+                .zip(prev.iter())
+                .all(|(a, b)| log2_bucket(*a) == log2_bucket(*b))
+            {
+                // The pattern repeats every `period` intervals, so the next
+                // one matches the first interval of the latest period.
+                return suffix[0];
+            }
+        }
+        self.ema
+    }
+}
+
+/// Coarse log2 bucket of an interval, used to tolerate jitter when matching
+/// a repeating period.
+fn log2_bucket(interval: u64) -> u32 {
+    u64::BITS - interval.max(1).leading_zeros()
+}
+
+static IRQ_TIMINGS: [SpinNoIrq<IrqTiming>; MAX_IRQ_COUNT] =
+    [const { SpinNoIrq::new(IrqTiming::new()) }; MAX_IRQ_COUNT];
+
+/// Returns a monotonic timestamp, in `time` CSR ticks.
+pub fn now() -> u64 {
+    riscv::register::time::read64()
+}
+
+/// Records a delivered interrupt on `irq` at `ts` for future prediction.
+pub fn record(irq: usize, ts: u64) {
+    IRQ_TIMINGS[irq].lock().record(ts);
+}
+
+/// Returns the earliest predicted deadline across all tracked sources, or
+/// `None` if none has enough history yet.
+pub fn next_event() -> Option<u64> {
+    IRQ_TIMINGS
+        .iter()
+        .filter_map(|t| t.lock().predict_next())
+        .min()
+}